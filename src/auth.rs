@@ -0,0 +1,22 @@
+//! How [`Client::request`](crate::Client::request) attaches the access token to authenticated
+//! requests.
+
+/// Selects how an authenticated request carries its access token.
+///
+/// The Matrix spec recommends the `Authorization: Bearer <token>` header, which doesn't end up
+/// in server access logs or intermediate proxies the way a query parameter does. Some older
+/// homeservers and application services only look for `access_token` on the query string, so
+/// that form is kept available as a fallback.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AuthStrategy {
+    /// Send the token via an `Authorization: Bearer` header. This is the default.
+    Header,
+    /// Send the token via an `access_token` query parameter.
+    QueryParameter,
+}
+
+impl Default for AuthStrategy {
+    fn default() -> Self {
+        AuthStrategy::Header
+    }
+}
@@ -0,0 +1,59 @@
+//! Materializes the `Room` state the client has accumulated from sync responses so far.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use ruma_identifiers::RoomId;
+
+use crate::api::r0::sync::sync_events::Response as SyncResponse;
+use crate::room::Room;
+
+/// Folds sync responses into a `RoomId`-keyed store of `Room` state.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BaseClient {
+    rooms: Arc<RwLock<HashMap<RoomId, Room>>>,
+}
+
+impl BaseClient {
+    /// Folds a single sync response's joined, invited and left room state into the store.
+    pub(crate) fn handle_sync_response(&self, response: &SyncResponse) {
+        let mut rooms = self.rooms.write().expect("rooms lock was poisoned");
+
+        for (room_id, joined_room) in &response.rooms.join {
+            let room = rooms
+                .entry(room_id.clone())
+                .or_insert_with(|| Room::new(room_id.clone()));
+
+            for event in joined_room.state.events.iter().chain(&joined_room.timeline.events) {
+                room.handle_event(event);
+            }
+
+            room.prev_batch = joined_room.timeline.prev_batch.clone();
+        }
+
+        for (room_id, invited_room) in &response.rooms.invite {
+            let room = rooms
+                .entry(room_id.clone())
+                .or_insert_with(|| Room::new(room_id.clone()));
+
+            for event in &invited_room.invite_state.events {
+                room.handle_stripped_state_event(event);
+            }
+        }
+
+        for (room_id, left_room) in &response.rooms.leave {
+            let room = rooms
+                .entry(room_id.clone())
+                .or_insert_with(|| Room::new(room_id.clone()));
+
+            for event in &left_room.timeline.events {
+                room.handle_event(event);
+            }
+        }
+    }
+
+    /// Returns a shared handle to the accumulated room store.
+    pub(crate) fn rooms(&self) -> Arc<RwLock<HashMap<RoomId, Room>>> {
+        self.rooms.clone()
+    }
+}
@@ -0,0 +1,73 @@
+//! Event-handler registry used by [`Client::sync_forever`](crate::Client::sync_forever) to
+//! dispatch timeline and state events to callbacks as they arrive, instead of making callers
+//! hand-walk every `sync_events::Response`.
+
+use std::collections::HashMap;
+
+use ruma_events::{collections::all::RoomEvent, stripped::AnyStrippedStateEvent, EventType};
+use ruma_identifiers::RoomId;
+
+/// A callback invoked with the room an event occurred in and the event itself.
+pub type EventHandler = Box<dyn FnMut(&RoomId, &RoomEvent) + Send>;
+
+/// A callback invoked with the room an invite was received in and one of the stripped state
+/// events from that invite's `invite_state`.
+pub type InviteEventHandler = Box<dyn FnMut(&RoomId, &AnyStrippedStateEvent) + Send>;
+
+/// Handlers registered via [`Client::on_room_event`](crate::Client::on_room_event) and
+/// [`Client::on_invite_event`](crate::Client::on_invite_event), keyed by the event type they were
+/// registered for.
+#[derive(Default)]
+pub(crate) struct EventHandlers {
+    handlers: HashMap<EventType, Vec<EventHandler>>,
+    invite_handlers: HashMap<EventType, Vec<InviteEventHandler>>,
+}
+
+impl EventHandlers {
+    /// Registers `handler` to be invoked for every event of type `event_type`.
+    pub(crate) fn add(&mut self, event_type: EventType, handler: EventHandler) {
+        self.handlers
+            .entry(event_type)
+            .or_insert_with(Vec::new)
+            .push(handler);
+    }
+
+    /// Registers `handler` to be invoked for every invite's stripped state event of type
+    /// `event_type`.
+    pub(crate) fn add_invite(&mut self, event_type: EventType, handler: InviteEventHandler) {
+        self.invite_handlers
+            .entry(event_type)
+            .or_insert_with(Vec::new)
+            .push(handler);
+    }
+
+    /// Invokes every handler registered for `event`'s type.
+    pub(crate) fn dispatch(&mut self, room_id: &RoomId, event: &RoomEvent) {
+        if let Some(handlers) = self.handlers.get_mut(&event.event_type()) {
+            for handler in handlers {
+                handler(room_id, event);
+            }
+        }
+    }
+
+    /// Invokes every invite handler registered for `event`'s type.
+    pub(crate) fn dispatch_invite(&mut self, room_id: &RoomId, event: &AnyStrippedStateEvent) {
+        if let Some(handlers) = self.invite_handlers.get_mut(&event.event_type()) {
+            for handler in handlers {
+                handler(room_id, event);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for EventHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventHandlers")
+            .field("event_types", &self.handlers.keys().collect::<Vec<_>>())
+            .field(
+                "invite_event_types",
+                &self.invite_handlers.keys().collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
@@ -31,10 +31,10 @@
 //!
 //! ```no_run
 //! # use futures::{Future, Stream};
-//! # use ruma_client::Client;
+//! # use ruma_client::{Client, SyncSettings};
 //! # let homeserver_url = "https://example.com".parse().unwrap();
 //! # let client = Client::https(homeserver_url, None).unwrap();
-//! let work = client.sync(None, None, true).map(|response| {
+//! let work = client.sync(SyncSettings::new()).map(|response| {
 //!   // Do something with the data in the response...
 //!     # Ok::<(), ruma_client::Error>(())
 //! });
@@ -101,33 +101,58 @@
 )]
 
 use std::{
+    collections::HashMap,
     convert::TryInto,
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
 };
 
 use futures::{
-    future::{Future, FutureFrom, IntoFuture},
+    future::{self, loop_fn, Either, Future, FutureFrom, IntoFuture, Loop},
     stream::{self, Stream},
 };
 use hyper::{
     client::{connect::Connect, HttpConnector},
-    Client as HyperClient, Uri,
+    header::{HeaderValue, AUTHORIZATION},
+    Body, Client as HyperClient, Uri,
 };
 #[cfg(feature = "hyper-tls")]
 use hyper_tls::HttpsConnector;
 #[cfg(feature = "hyper-tls")]
 use native_tls::Error as NativeTlsError;
 use ruma_api::Endpoint;
+use ruma_events::{collections::all::RoomEvent, stripped::AnyStrippedStateEvent, EventType};
+use ruma_identifiers::RoomId;
+use tokio_timer::Delay;
 use url::Url;
 
+use crate::base_client::BaseClient;
 use crate::error::InnerError;
-pub use crate::{error::Error, session::Session};
+use crate::handlers::EventHandlers;
+use crate::retry::{is_transient, is_transient_transport_error, MatrixErrorBody};
+pub use crate::{
+    auth::AuthStrategy,
+    error::Error,
+    media::ThumbnailMethod,
+    retry::RetryConfig,
+    room::{Member, Room},
+    session::Session,
+    sync_settings::{LoopCtrl, SyncSettings},
+};
 
 /// Matrix client-server API endpoints.
 pub mod api;
+mod auth;
+mod base_client;
 mod error;
+mod handlers;
+mod media;
+mod retry;
+mod room;
 mod session;
+mod sso;
+mod sync_settings;
 
 /// A client for the Matrix client-server API.
 #[derive(Debug)]
@@ -145,6 +170,14 @@ where
     hyper: HyperClient<C>,
     /// User session data.
     session: Mutex<Option<Session>>,
+    /// Callbacks registered via `on_room_event`, dispatched as `sync_forever` consumes events.
+    handlers: Mutex<EventHandlers>,
+    /// How `request` retries failed requests.
+    retry_config: RetryConfig,
+    /// How `request` attaches the access token to authenticated requests.
+    auth_strategy: AuthStrategy,
+    /// Room state materialized from sync responses.
+    base_client: BaseClient,
 }
 
 impl Client<HttpConnector> {
@@ -154,6 +187,10 @@ impl Client<HttpConnector> {
             homeserver_url,
             hyper: HyperClient::builder().keep_alive(true).build_http(),
             session: Mutex::new(session),
+            handlers: Mutex::new(EventHandlers::default()),
+            retry_config: RetryConfig::default(),
+            auth_strategy: AuthStrategy::default(),
+            base_client: BaseClient::default(),
         }))
     }
 
@@ -179,6 +216,10 @@ impl Client<HttpsConnector<HttpConnector>> {
             homeserver_url,
             hyper: { HyperClient::builder().keep_alive(true).build(connector) },
             session: Mutex::new(session),
+            handlers: Mutex::new(EventHandlers::default()),
+            retry_config: RetryConfig::default(),
+            auth_strategy: AuthStrategy::default(),
+            base_client: BaseClient::default(),
         })))
     }
 }
@@ -189,16 +230,24 @@ where
 {
     /// Creates a new client using the given `hyper::Client`.
     ///
-    /// This allows the user to configure the details of HTTP as desired.
+    /// This allows the user to configure the details of HTTP as desired, including how failed
+    /// requests are retried via `retry_config` and how the access token is attached to
+    /// authenticated requests via `auth_strategy`.
     pub fn custom(
         hyper_client: HyperClient<C>,
         homeserver_url: Url,
         session: Option<Session>,
+        retry_config: RetryConfig,
+        auth_strategy: AuthStrategy,
     ) -> Self {
         Self(Arc::new(ClientData {
             homeserver_url,
             hyper: hyper_client,
             session: Mutex::new(session),
+            handlers: Mutex::new(EventHandlers::default()),
+            retry_config,
+            auth_strategy,
+            base_client: BaseClient::default(),
         }))
     }
 
@@ -240,6 +289,64 @@ where
         })
     }
 
+    /// Completes an SSO login by exchanging a `loginToken` (as returned by the homeserver's
+    /// `/login/sso/redirect` flow) for a session.
+    ///
+    /// In contrast to api::r0::session::login::call(), this method stores the session data
+    /// returned by the endpoint in this client, instead of returning it.
+    pub fn login_with_token(
+        &self,
+        token: String,
+        device_id: Option<String>,
+    ) -> impl Future<Item = Session, Error = Error> {
+        use crate::api::r0::session::login;
+
+        let data = self.0.clone();
+
+        login::call(
+            self.clone(),
+            login::Request {
+                address: None,
+                login_type: login::LoginType::Token,
+                medium: None,
+                device_id,
+                password: None,
+                token: Some(token),
+                user: None,
+            },
+        )
+        .map(move |response| {
+            let session = Session {
+                access_token: response.access_token,
+                device_id: response.device_id,
+                user_id: response.user_id,
+            };
+            *data.session.lock().unwrap() = Some(session.clone());
+
+            session
+        })
+    }
+
+    /// Begins an SSO login flow against an optional identity provider `idp_id`.
+    ///
+    /// Spins up an ephemeral local HTTP listener to receive the homeserver's redirect, and
+    /// returns the URL the caller should open in a browser alongside a future that resolves to
+    /// the logged-in `Session` once the provider redirects back with a login token. The token is
+    /// exchanged for a session via `login_with_token`, exactly like `log_in` stores its session.
+    pub fn login_with_sso(
+        &self,
+        idp_id: Option<&str>,
+        device_id: Option<String>,
+    ) -> Result<(Url, impl Future<Item = Session, Error = Error>), Error> {
+        let (local_redirect_url, token) = sso::local_redirect_listener()?;
+        let sso_url = sso::redirect_url(&self.0.homeserver_url, idp_id, &local_redirect_url);
+
+        let client = self.clone();
+        let session = token.and_then(move |token| client.login_with_token(token, device_id));
+
+        Ok((sso_url, session))
+    }
+
     /// Register as a guest. In contrast to api::r0::account::register::call(),
     /// this method stores the session data returned by the endpoint in this
     /// client, instead of returning it.
@@ -313,39 +420,122 @@ where
         })
     }
 
+    /// Uploads `content` to the homeserver's content repository, returning the `mxc://` URI it
+    /// was stored at.
+    pub fn upload(
+        &self,
+        content_type: String,
+        content: Vec<u8>,
+    ) -> impl Future<Item = String, Error = Error> {
+        use crate::api::r0::media::create_content;
+
+        create_content::call(
+            self.clone(),
+            create_content::Request {
+                content_type: Some(content_type),
+                filename: None,
+                file: content,
+            },
+        )
+        .map(|response| response.content_uri)
+    }
+
+    /// Downloads the content stored at `mxc_uri` from the homeserver's content repository.
+    pub fn download(&self, mxc_uri: &str) -> impl Future<Item = Vec<u8>, Error = Error> {
+        use crate::api::r0::media::get_content;
+
+        media::parse_mxc_uri(mxc_uri)
+            .into_future()
+            .and_then({
+                let client = self.clone();
+                move |(server_name, media_id)| {
+                    get_content::call(
+                        client,
+                        get_content::Request {
+                            server_name,
+                            media_id,
+                        },
+                    )
+                }
+            })
+            .map(|response| response.file)
+    }
+
+    /// Downloads a thumbnail of the content stored at `mxc_uri`, resized to fit `width` x
+    /// `height` according to `method`.
+    pub fn download_thumbnail(
+        &self,
+        mxc_uri: &str,
+        width: u32,
+        height: u32,
+        method: ThumbnailMethod,
+    ) -> impl Future<Item = Vec<u8>, Error = Error> {
+        use crate::api::r0::media::get_content_thumbnail;
+
+        let method = match method {
+            ThumbnailMethod::Crop => get_content_thumbnail::Method::Crop,
+            ThumbnailMethod::Scale => get_content_thumbnail::Method::Scale,
+        };
+
+        media::parse_mxc_uri(mxc_uri)
+            .into_future()
+            .and_then({
+                let client = self.clone();
+                move |(server_name, media_id)| {
+                    get_content_thumbnail::call(
+                        client,
+                        get_content_thumbnail::Request {
+                            server_name,
+                            media_id,
+                            width,
+                            height,
+                            method: Some(method),
+                        },
+                    )
+                }
+            })
+            .map(|response| response.file)
+    }
+
     /// Convenience method that represents repeated calls to the sync_events endpoint as a stream.
     ///
-    /// If the since parameter is None, the first Item might take a significant time to arrive and
-    /// be deserialized, because it contains all events that have occured in the whole lifetime of
-    /// the logged-in users account and are visible to them.
+    /// If `settings` doesn't carry a `since` token, the first Item might take a significant time
+    /// to arrive and be deserialized, because it contains all events that have occured in the
+    /// whole lifetime of the logged-in users account and are visible to them.
     pub fn sync(
         &self,
-        filter: Option<api::r0::sync::sync_events::Filter>,
-        since: Option<String>,
-        set_presence: bool,
+        settings: SyncSettings,
     ) -> impl Stream<Item = api::r0::sync::sync_events::Response, Error = Error> {
         use crate::api::r0::sync::sync_events;
 
         let client = self.clone();
-        let set_presence = if set_presence {
+        let data = self.0.clone();
+        let filter = settings.filter;
+        let full_state = settings.full_state;
+        let timeout = settings.timeout;
+        let set_presence = if settings.set_presence {
             None
         } else {
             Some(sync_events::SetPresence::Offline)
         };
 
-        stream::unfold(since, move |since| {
+        stream::unfold(settings.since, move |since| {
+            let data = data.clone();
+
             Some(
                 sync_events::call(
                     client.clone(),
                     sync_events::Request {
                         filter: filter.clone(),
                         since,
-                        full_state: None,
+                        full_state: Some(full_state),
                         set_presence: set_presence.clone(),
-                        timeout: None,
+                        timeout,
                     },
                 )
-                .map(|res| {
+                .map(move |res| {
+                    data.base_client.handle_sync_response(&res);
+
                     let next_batch_clone = res.next_batch.clone();
                     (res, Some(next_batch_clone))
                 }),
@@ -353,7 +543,127 @@ where
         })
     }
 
-    /// Makes a request to a Matrix API endpoint.
+    /// Returns a shared handle to the room state the client has accumulated from sync responses
+    /// so far, keyed by room id.
+    pub fn get_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Room>>> {
+        self.0.base_client.rooms()
+    }
+
+    /// Returns a snapshot of a single room's accumulated state, if the client has seen it in a
+    /// sync response.
+    pub fn get_room(&self, room_id: &RoomId) -> Option<Room> {
+        self.0
+            .base_client
+            .rooms()
+            .read()
+            .expect("rooms lock was poisoned")
+            .get(room_id)
+            .cloned()
+    }
+
+    /// Registers `handler` to be invoked with the room and event for every event of type
+    /// `event_type` seen while consuming sync responses via `sync_forever`.
+    ///
+    /// This lets a bot or integration be written as a set of callbacks instead of manually
+    /// walking every `sync_events::Response`.
+    pub fn on_room_event<F>(&self, event_type: EventType, handler: F)
+    where
+        F: FnMut(&RoomId, &RoomEvent) + Send + 'static,
+    {
+        self.0
+            .handlers
+            .lock()
+            .expect("handlers mutex was poisoned")
+            .add(event_type, Box::new(handler));
+    }
+
+    /// Registers `handler` to be invoked with the room and stripped state event for every
+    /// `invite_state` event of type `event_type` seen while consuming sync responses via
+    /// `sync_forever`.
+    ///
+    /// Room invites only carry stripped state events (the room's current state, not a full
+    /// timeline), which is why this is separate from `on_room_event` rather than sharing it.
+    pub fn on_invite_event<F>(&self, event_type: EventType, handler: F)
+    where
+        F: FnMut(&RoomId, &AnyStrippedStateEvent) + Send + 'static,
+    {
+        self.0
+            .handlers
+            .lock()
+            .expect("handlers mutex was poisoned")
+            .add_invite(event_type, Box::new(handler));
+    }
+
+    /// Continually syncs with the homeserver starting from `settings`, dispatching the timeline
+    /// and state events of joined and left rooms, and the stripped state events of invited
+    /// rooms, to any handlers registered via `on_room_event`/`on_invite_event`, then invoking
+    /// `callback` with the response.
+    ///
+    /// The `since` token is persisted between iterations automatically, so `callback` only needs
+    /// to decide whether the loop should keep going, by returning `LoopCtrl::Continue` or
+    /// `LoopCtrl::Break`.
+    pub fn sync_forever<F>(
+        &self,
+        settings: SyncSettings,
+        mut callback: F,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        F: FnMut(api::r0::sync::sync_events::Response) -> LoopCtrl + Send + 'static,
+    {
+        let client = self.clone();
+        let data = self.0.clone();
+
+        loop_fn(settings, move |settings| {
+            let data = data.clone();
+
+            client
+                .sync(settings.clone())
+                .into_future()
+                .map_err(|(error, _rest)| error)
+                .and_then(move |(response, _rest)| {
+                    let response = response.expect("the sync stream never ends on its own");
+
+                    {
+                        let mut handlers =
+                            data.handlers.lock().expect("handlers mutex was poisoned");
+
+                        for (room_id, joined_room) in &response.rooms.join {
+                            for event in &joined_room.timeline.events {
+                                handlers.dispatch(room_id, event);
+                            }
+                            for event in &joined_room.state.events {
+                                handlers.dispatch(room_id, event);
+                            }
+                        }
+
+                        for (room_id, invited_room) in &response.rooms.invite {
+                            for event in &invited_room.invite_state.events {
+                                handlers.dispatch_invite(room_id, event);
+                            }
+                        }
+
+                        for (room_id, left_room) in &response.rooms.leave {
+                            for event in &left_room.timeline.events {
+                                handlers.dispatch(room_id, event);
+                            }
+                            for event in &left_room.state.events {
+                                handlers.dispatch(room_id, event);
+                            }
+                        }
+                    }
+
+                    let next_settings = settings.token(response.next_batch.clone());
+
+                    Ok(match callback(response) {
+                        LoopCtrl::Continue => Loop::Continue(next_settings),
+                        LoopCtrl::Break => Loop::Break(()),
+                    })
+                })
+        })
+    }
+
+    /// Makes a request to a Matrix API endpoint, retrying transient failures according to the
+    /// client's `RetryConfig`.
     pub(crate) fn request<E>(
         self,
         request: <E as Endpoint>::Request,
@@ -365,24 +675,52 @@ where
         let data2 = self.0.clone();
         let mut url = self.0.homeserver_url.clone();
 
+        // The sync endpoint already long-polls with its own timeout, so it isn't also wrapped
+        // in exponential-backoff retries.
+        let retry_config = if is_long_poll::<E>() {
+            RetryConfig::none()
+        } else {
+            data1.retry_config
+        };
+
         request
             .try_into()
             .map_err(Error::from)
             .into_future()
-            .and_then(move |hyper_request| {
+            .and_then(move |mut hyper_request| {
                 {
                     let uri = hyper_request.uri();
 
                     url.set_path(uri.path());
                     url.set_query(uri.query());
+                }
 
-                    if E::METADATA.requires_authentication {
-                        if let Some(ref session) = *data1.session.lock().unwrap() {
-                            url.query_pairs_mut()
-                                .append_pair("access_token", &session.access_token);
-                        } else {
-                            return Err(Error(InnerError::AuthenticationRequired));
+                if E::METADATA.requires_authentication {
+                    if let Some(ref session) = *data1.session.lock().unwrap() {
+                        match data1.auth_strategy {
+                            AuthStrategy::Header => {
+                                let value = match HeaderValue::from_str(&format!(
+                                    "Bearer {}",
+                                    session.access_token
+                                )) {
+                                    Ok(value) => value,
+                                    // The Matrix spec doesn't constrain access tokens to
+                                    // header-safe bytes, so a token that doesn't fit in a
+                                    // header value is a client error, not a bug to panic on.
+                                    Err(_) => {
+                                        return Err(Error(InnerError::InvalidAccessToken))
+                                    }
+                                };
+
+                                hyper_request.headers_mut().insert(AUTHORIZATION, value);
+                            }
+                            AuthStrategy::QueryParameter => {
+                                url.query_pairs_mut()
+                                    .append_pair("access_token", &session.access_token);
+                            }
                         }
+                    } else {
+                        return Err(Error(InnerError::AuthenticationRequired));
                     }
                 }
 
@@ -393,7 +731,82 @@ where
             .and_then(move |(uri, mut hyper_request)| {
                 *hyper_request.uri_mut() = uri;
 
-                data2.hyper.request(hyper_request).map_err(Error::from)
+                let is_idempotent = hyper_request.method() == hyper::Method::GET;
+
+                // `http::request::Parts` (via its `Extensions`) isn't `Clone`, so we can't hang
+                // on to it and clone it for every retry attempt below. Pull out just the pieces
+                // of the request that are both `Clone` and worth resending as-is instead.
+                let (parts, body) = hyper_request.into_parts();
+                let method = parts.method;
+                let uri = parts.uri;
+                let headers = parts.headers;
+
+                body.concat2()
+                    .map_err(Error::from)
+                    .map(move |body| (method, uri, headers, body.into_bytes(), is_idempotent))
+            })
+            .and_then(move |(method, uri, headers, body, is_idempotent)| {
+                loop_fn(0u32, move |attempt| {
+                    let data = data2.clone();
+                    let method = method.clone();
+                    let uri = uri.clone();
+                    let headers = headers.clone();
+                    let body = body.clone();
+
+                    let mut hyper_request = hyper::Request::new(Body::from(body));
+                    *hyper_request.method_mut() = method;
+                    *hyper_request.uri_mut() = uri;
+                    *hyper_request.headers_mut() = headers;
+
+                    data.hyper.request(hyper_request).then(move |result| {
+                        let hyper_response = match result {
+                            Ok(hyper_response) => hyper_response,
+                            // A connection failure (refused, reset, DNS, ...) is just as
+                            // transient as a 5xx response, so it goes through the same
+                            // backoff path instead of failing the whole request outright.
+                            Err(error) => {
+                                let should_retry = attempt < retry_config.max_retries
+                                    && is_transient_transport_error(&error)
+                                    && (is_idempotent || retry_config.retry_non_idempotent);
+
+                                if !should_retry {
+                                    return Either::A(future::err(Error::from(error)));
+                                }
+
+                                let delay = retry_config.backoff(attempt);
+
+                                return Either::B(Either::B(
+                                    Delay::new(Instant::now() + delay)
+                                        .map_err(Error::from)
+                                        .map(move |()| Loop::Continue(attempt + 1)),
+                                ));
+                            }
+                        };
+
+                        let should_retry = attempt < retry_config.max_retries
+                            && is_transient(hyper_response.status())
+                            && (is_idempotent || retry_config.retry_non_idempotent);
+
+                        if !should_retry {
+                            return Either::A(future::ok(Loop::Break(hyper_response)));
+                        }
+
+                        Either::B(Either::A(
+                            hyper_response.into_body().concat2().map_err(Error::from).and_then(
+                                move |chunk| {
+                                    let delay = serde_json::from_slice::<MatrixErrorBody>(&chunk)
+                                        .ok()
+                                        .and_then(|body| body.retry_after())
+                                        .unwrap_or_else(|| retry_config.backoff(attempt));
+
+                                    Delay::new(Instant::now() + delay)
+                                        .map_err(Error::from)
+                                        .map(move |()| Loop::Continue(attempt + 1))
+                                },
+                            ),
+                        ))
+                    })
+                })
             })
             .and_then(|hyper_response| {
                 E::Response::future_from(hyper_response).map_err(Error::from)
@@ -401,6 +814,12 @@ where
     }
 }
 
+/// Whether `E` is the long-polling `/sync` endpoint, whose own timeout shouldn't be compounded
+/// with exponential-backoff retries.
+fn is_long_poll<E: Endpoint>() -> bool {
+    E::METADATA.path.ends_with("/sync")
+}
+
 impl<C: Connect> Clone for Client<C> {
     fn clone(&self) -> Self {
         Self(self.0.clone())
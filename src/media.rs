@@ -0,0 +1,70 @@
+//! Convenience methods for the content repository, as used for uploading and downloading
+//! attachments and avatars.
+
+use crate::error::InnerError;
+use crate::Error;
+
+/// How the homeserver should resize a thumbnail to fit the requested dimensions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ThumbnailMethod {
+    /// Crop the image to the exact requested dimensions.
+    Crop,
+    /// Scale the image down to fit within the requested dimensions, preserving aspect ratio.
+    Scale,
+}
+
+/// Splits an `mxc://server.name/media_id` URI into its `(server_name, media_id)` parts, as
+/// needed to build the `/_matrix/media/r0/download/{server}/{media_id}` request path.
+pub(crate) fn parse_mxc_uri(mxc_uri: &str) -> Result<(String, String), Error> {
+    let rest = match mxc_uri.strip_prefix("mxc://") {
+        Some(rest) => rest,
+        None => return Err(Error(InnerError::InvalidMxcUri)),
+    };
+
+    let mut parts = rest.splitn(2, '/');
+    let server_name = parts.next().filter(|s| !s.is_empty());
+    // The media id is a single opaque path segment, so a stray `/` in it (e.g. the
+    // `mxc://server.name//` produced by an empty media id) means the URI is malformed rather
+    // than naming a media id that happens to contain a slash.
+    let media_id = parts.next().filter(|s| !s.is_empty() && !s.contains('/'));
+
+    match (server_name, media_id) {
+        (Some(server_name), Some(media_id)) => Ok((server_name.to_owned(), media_id.to_owned())),
+        _ => Err(Error(InnerError::InvalidMxcUri)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_mxc_uri;
+
+    #[test]
+    fn parses_a_valid_uri() {
+        assert_eq!(
+            parse_mxc_uri("mxc://server.name/media_id").unwrap(),
+            ("server.name".to_owned(), "media_id".to_owned())
+        );
+    }
+
+    #[test]
+    fn rejects_a_missing_scheme() {
+        assert!(parse_mxc_uri("server.name/media_id").is_err());
+    }
+
+    #[test]
+    fn rejects_a_missing_media_id() {
+        assert!(parse_mxc_uri("mxc://server.name").is_err());
+        assert!(parse_mxc_uri("mxc://server.name/").is_err());
+    }
+
+    #[test]
+    fn rejects_a_missing_server_name() {
+        assert!(parse_mxc_uri("mxc:///media_id").is_err());
+    }
+
+    #[test]
+    fn rejects_a_media_id_with_an_embedded_slash() {
+        assert!(parse_mxc_uri("mxc://server.name//").is_err());
+        assert!(parse_mxc_uri("mxc://server.name/media/id").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! Retry behaviour for [`Client::request`](crate::Client::request).
+
+use std::cmp::min;
+use std::time::Duration;
+
+use hyper::StatusCode;
+use serde::Deserialize;
+
+/// The body of a Matrix error response, as returned alongside a non-2xx status code.
+#[derive(Debug, Deserialize)]
+pub(crate) struct MatrixErrorBody {
+    /// The machine-readable error code, e.g. `M_LIMIT_EXCEEDED`.
+    errcode: String,
+    /// For `M_LIMIT_EXCEEDED` errors, how long the caller should wait before retrying.
+    retry_after_ms: Option<u64>,
+}
+
+impl MatrixErrorBody {
+    /// The delay the homeserver asked us to wait before retrying, if this is a rate-limit error
+    /// that specified one.
+    pub(crate) fn retry_after(&self) -> Option<Duration> {
+        if self.errcode == "M_LIMIT_EXCEEDED" {
+            self.retry_after_ms.map(Duration::from_millis)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `status` represents a failure that's worth retrying rather than surfacing directly.
+pub(crate) fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Whether `error` represents a transport-level failure (e.g. a dropped or refused connection)
+/// that's worth retrying with backoff, as opposed to e.g. a malformed request we constructed
+/// ourselves, which would just fail the same way again.
+pub(crate) fn is_transient_transport_error(error: &hyper::Error) -> bool {
+    error.is_connect() || error.is_closed() || error.is_incomplete_message()
+}
+
+/// Configures how [`Client::request`](crate::Client::request) retries failed requests.
+///
+/// Requests that come back with HTTP 429 and a `retry_after_ms` hint are retried after that
+/// exact delay. Other transient failures (5xx responses and connection errors) are retried with
+/// an exponential backoff, starting at `base_delay` and capped at `max_delay`.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryConfig {
+    /// The maximum number of attempts made after the first request fails.
+    pub(crate) max_retries: u32,
+    /// The delay before the first retry, doubling on every subsequent attempt.
+    pub(crate) base_delay: Duration,
+    /// The maximum delay between retries, regardless of how many attempts have been made.
+    pub(crate) max_delay: Duration,
+    /// Whether to also retry requests that aren't guaranteed to be idempotent.
+    pub(crate) retry_non_idempotent: bool,
+}
+
+impl RetryConfig {
+    /// Creates a `RetryConfig` that retries up to `max_retries` times, using the library's
+    /// default backoff parameters.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Self::default()
+        }
+    }
+
+    /// A `RetryConfig` that never retries, surfacing the first failure directly.
+    pub fn none() -> Self {
+        Self::new(0)
+    }
+
+    /// Sets the delay before the first retry attempt.
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Sets the maximum delay between retries.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Sets whether requests that aren't guaranteed to be idempotent (i.e. anything but `GET`)
+    /// should also be retried on transient failure.
+    pub fn retry_non_idempotent(mut self, retry_non_idempotent: bool) -> Self {
+        self.retry_non_idempotent = retry_non_idempotent;
+        self
+    }
+
+    /// The delay to wait before retrying for the given attempt number (0-indexed).
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let scaled = self
+            .base_delay
+            .checked_mul(1u32.checked_shl(attempt).unwrap_or(u32::max_value()))
+            .unwrap_or(self.max_delay);
+
+        min(scaled, self.max_delay)
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{MatrixErrorBody, RetryConfig};
+
+    #[test]
+    fn backoff_doubles_with_every_attempt() {
+        let config = RetryConfig::new(10).base_delay(Duration::from_millis(500));
+
+        assert_eq!(config.backoff(0), Duration::from_millis(500));
+        assert_eq!(config.backoff(1), Duration::from_millis(1000));
+        assert_eq!(config.backoff(2), Duration::from_millis(2000));
+        assert_eq!(config.backoff(3), Duration::from_millis(4000));
+    }
+
+    #[test]
+    fn backoff_is_capped_at_max_delay() {
+        let config = RetryConfig::new(40)
+            .base_delay(Duration::from_millis(500))
+            .max_delay(Duration::from_secs(5));
+
+        assert_eq!(config.backoff(10), Duration::from_secs(5));
+        // `1u32 << 32` is an invalid shift amount, so `checked_shl` returns `None` here; that
+        // should still clamp to `max_delay` rather than panicking.
+        assert_eq!(config.backoff(32), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn retry_after_is_parsed_for_rate_limit_errors() {
+        let body: MatrixErrorBody = serde_json::from_str(
+            r#"{"errcode": "M_LIMIT_EXCEEDED", "error": "Too many requests", "retry_after_ms": 2000}"#,
+        )
+        .unwrap();
+
+        assert_eq!(body.retry_after(), Some(Duration::from_millis(2000)));
+    }
+
+    #[test]
+    fn retry_after_is_none_without_a_hint() {
+        let body: MatrixErrorBody =
+            serde_json::from_str(r#"{"errcode": "M_LIMIT_EXCEEDED", "error": "Too many requests"}"#)
+                .unwrap();
+
+        assert_eq!(body.retry_after(), None);
+    }
+
+    #[test]
+    fn retry_after_is_none_for_other_errors() {
+        let body: MatrixErrorBody = serde_json::from_str(
+            r#"{"errcode": "M_FORBIDDEN", "error": "Forbidden", "retry_after_ms": 2000}"#,
+        )
+        .unwrap();
+
+        assert_eq!(body.retry_after(), None);
+    }
+}
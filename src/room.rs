@@ -0,0 +1,152 @@
+//! Room state derived from sync responses, as accumulated by the client's base-client layer.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use ruma_events::{
+    collections::all::RoomEvent, room::member::MembershipState, stripped::AnyStrippedStateEvent,
+};
+use ruma_identifiers::{RoomId, UserId};
+
+/// A room member, as derived from its `m.room.member` state events.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Member {
+    /// The member's display name, if they have set one.
+    pub display_name: Option<String>,
+    /// The member's current membership state.
+    pub membership: MembershipState,
+}
+
+/// A room's state, accumulated from the `m.room.*` state events seen while syncing.
+///
+/// This saves bot and UI authors from re-deriving a room's name and membership from every sync
+/// response themselves.
+#[derive(Clone, Debug)]
+pub struct Room {
+    /// The room's id.
+    pub room_id: RoomId,
+    /// The explicit `m.room.name`, if set.
+    name: Option<String>,
+    /// The `m.room.canonical_alias`, if set.
+    canonical_alias: Option<String>,
+    /// The `m.room.topic`, if set.
+    pub topic: Option<String>,
+    /// The members of the room, keyed by user id.
+    pub members: HashMap<UserId, Member>,
+    /// The most recent `prev_batch` token seen for this room's timeline.
+    pub prev_batch: Option<String>,
+}
+
+impl Room {
+    /// Creates an empty `Room` with no state folded in yet.
+    pub(crate) fn new(room_id: RoomId) -> Self {
+        Self {
+            room_id,
+            name: None,
+            canonical_alias: None,
+            topic: None,
+            members: HashMap::new(),
+            prev_batch: None,
+        }
+    }
+
+    /// Folds a single timeline or state event into the room's accumulated state.
+    pub(crate) fn handle_event(&mut self, event: &RoomEvent) {
+        match event {
+            RoomEvent::RoomName(event) => {
+                self.name = Some(event.content.name.clone());
+            }
+            RoomEvent::RoomCanonicalAlias(event) => {
+                self.canonical_alias = event.content.alias.as_ref().map(ToString::to_string);
+            }
+            RoomEvent::RoomTopic(event) => {
+                self.topic = Some(event.content.topic.clone());
+            }
+            RoomEvent::RoomMember(event) => {
+                if let Ok(user_id) = UserId::try_from(event.state_key.as_str()) {
+                    self.members.insert(
+                        user_id,
+                        Member {
+                            display_name: event.content.displayname.clone(),
+                            membership: event.content.membership,
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Folds a single stripped state event from an invite's `invite_state` into the room's
+    /// accumulated state.
+    ///
+    /// Stripped state events only carry the room's current state, not a full timeline
+    /// `RoomEvent`, so an invited room gets this instead of `handle_event` until it's joined.
+    pub(crate) fn handle_stripped_state_event(&mut self, event: &AnyStrippedStateEvent) {
+        match event {
+            AnyStrippedStateEvent::RoomName(event) => {
+                self.name = Some(event.content.name.clone());
+            }
+            AnyStrippedStateEvent::RoomCanonicalAlias(event) => {
+                self.canonical_alias = event.content.alias.as_ref().map(ToString::to_string);
+            }
+            AnyStrippedStateEvent::RoomTopic(event) => {
+                self.topic = Some(event.content.topic.clone());
+            }
+            AnyStrippedStateEvent::RoomMember(event) => {
+                if let Ok(user_id) = UserId::try_from(event.state_key.as_str()) {
+                    self.members.insert(
+                        user_id,
+                        Member {
+                            display_name: event.content.displayname.clone(),
+                            membership: event.content.membership,
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Computes the room's display name using the standard Matrix fallback algorithm: the
+    /// explicit name, then the canonical alias, then a name derived from the other members
+    /// (excluding `own_user_id`, the logged-in user, so a DM doesn't render as the local user's
+    /// own name).
+    pub fn display_name(&self, own_user_id: &UserId) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+
+        if let Some(alias) = &self.canonical_alias {
+            return alias.clone();
+        }
+
+        let others: Vec<&Member> = self
+            .members
+            .iter()
+            .filter(|(user_id, _)| *user_id != own_user_id)
+            .filter(|(_, member)| {
+                member.membership == MembershipState::Join || member.membership == MembershipState::Invite
+            })
+            .map(|(_, member)| member)
+            .collect();
+
+        // The "and N others" count below is the true number of other joined/invited members,
+        // not just the ones we have names to list - a member with no display name set is still
+        // a member, and shouldn't silently vanish from the count.
+        let mut names: Vec<&str> = others
+            .iter()
+            .filter_map(|member| member.display_name.as_deref())
+            .collect();
+        names.sort_unstable();
+
+        match others.len() {
+            0 => "Empty room".to_owned(),
+            1..=5 => names.join(", "),
+            _ => {
+                let shown = &names[..names.len().min(4)];
+                format!("{} and {} others", shown.join(", "), others.len() - shown.len())
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! SSO login support, as an alternative to [`Client::log_in`](crate::Client::log_in) for
+//! homeservers that delegate authentication to an external identity provider.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::{
+    future::{self, Either},
+    sync::oneshot,
+    Future,
+};
+use hyper::{service::service_fn_ok, Body, Method, Response, Server, StatusCode};
+use tokio_timer::Delay;
+use url::Url;
+
+use crate::error::InnerError;
+use crate::Error;
+
+/// How long to keep the local redirect server alive after the login token has been captured, so
+/// the "you may now close this window" response has a chance to actually reach the browser
+/// before the listener (and the connection it's serving) gets dropped.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_millis(200);
+
+/// The query parameter the homeserver appends the login token to when it redirects back to us.
+const TOKEN_PARAM: &str = "loginToken";
+
+/// Builds the homeserver's `/login/sso/redirect` URL that should be opened in a browser to let
+/// the user authenticate with their identity provider, asking it to redirect back to
+/// `redirect_url` with a `loginToken` query parameter once that's done.
+pub(crate) fn redirect_url(homeserver_url: &Url, idp_id: Option<&str>, redirect_url: &Url) -> Url {
+    let mut url = homeserver_url.clone();
+
+    let mut path = String::from("/_matrix/client/r0/login/sso/redirect");
+    if let Some(idp_id) = idp_id {
+        path.push('/');
+        path.push_str(idp_id);
+    }
+    url.set_path(&path);
+
+    url.query_pairs_mut()
+        .append_pair("redirectUrl", redirect_url.as_str());
+
+    url
+}
+
+/// Spins up an ephemeral HTTP listener on `127.0.0.1` that waits for the homeserver to redirect
+/// the browser back with a `loginToken` query parameter.
+///
+/// Returns the URL the listener is reachable at (to pass as `redirect_url` to `redirect_url`
+/// above) and a future that resolves to the captured token once a request arrives.
+pub(crate) fn local_redirect_listener(
+) -> Result<(Url, impl Future<Item = String, Error = Error>), Error> {
+    let server = Server::try_bind(&"127.0.0.1:0".parse().unwrap())
+        .map_err(|error| Error(InnerError::SsoListenerFailed(Box::new(error))))?;
+    let local_addr = server.local_addr();
+
+    let token_tx = Arc::new(Mutex::new(None));
+    let (token_tx_handle, token_rx) = oneshot::channel::<String>();
+    *token_tx.lock().expect("token sender mutex was poisoned") = Some(token_tx_handle);
+
+    let serve = server.serve(move || {
+        let token_tx = token_tx.clone();
+
+        service_fn_ok(move |req| {
+            if req.method() == Method::GET {
+                let token = req.uri().query().and_then(|query| {
+                    url::form_urlencoded::parse(query.as_bytes())
+                        .find(|(key, _)| key == TOKEN_PARAM)
+                        .map(|(_, value)| value.into_owned())
+                });
+
+                if let Some(token) = token {
+                    if let Some(sender) = token_tx
+                        .lock()
+                        .expect("token sender mutex was poisoned")
+                        .take()
+                    {
+                        let _ = sender.send(token);
+                    }
+                }
+            }
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(
+                    "You may now close this window and return to the app.",
+                ))
+                .expect("building a static response never fails")
+        })
+    });
+
+    let redirect_url = Url::parse(&format!("http://{}/", local_addr))
+        .expect("a socket address always parses as a URL");
+
+    let token_future = token_rx
+        .map_err(|error| Error(InnerError::SsoListenerFailed(Box::new(error))))
+        .select(
+            serve
+                .map(|()| unreachable!("the local redirect server runs until the token arrives"))
+                .map_err(|error| Error(InnerError::SsoListenerFailed(Box::new(error)))),
+        )
+        .then(move |result| match result {
+            Ok((token, remaining_serve)) => {
+                // `select` hands back whichever future didn't resolve first - here that's the
+                // server, which may still have the confirmation response queued up to flush
+                // to the browser. Keep it alive a little longer instead of dropping it
+                // immediately, then return the token regardless of how that race finishes.
+                Either::A(
+                    remaining_serve
+                        .select2(Delay::new(Instant::now() + SHUTDOWN_GRACE_PERIOD))
+                        .then(move |_| Ok::<_, Error>(token)),
+                )
+            }
+            Err((error, _remaining_token_rx)) => Either::B(future::err(error)),
+        });
+
+    Ok((redirect_url, token_future))
+}
@@ -0,0 +1,87 @@
+//! Configuration for `Client::sync` and `Client::sync_forever`.
+
+use std::time::Duration;
+
+use crate::api::r0::sync::sync_events::Filter;
+
+/// The long-poll timeout `SyncSettings::new` defaults to, so that `sync_forever` doesn't
+/// busy-spin between iterations.
+const DEFAULT_SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Settings for a single sync request, built up via its setter methods and passed to
+/// `Client::sync` or `Client::sync_forever`.
+#[derive(Clone, Debug)]
+pub struct SyncSettings {
+    /// The filter to apply to the sync, if any.
+    pub(crate) filter: Option<Filter>,
+    /// The `since` token to resume syncing from, as returned by a previous sync's `next_batch`.
+    pub(crate) since: Option<String>,
+    /// Whether the response should include the full state for every room.
+    pub(crate) full_state: bool,
+    /// Whether to update the user's presence status as a result of this call.
+    pub(crate) set_presence: bool,
+    /// How long the server should long-poll for new events before returning an empty response.
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl SyncSettings {
+    /// Creates `SyncSettings` with no filter or `since` token, and the library's default
+    /// long-poll timeout.
+    pub fn new() -> Self {
+        Self {
+            filter: None,
+            since: None,
+            full_state: false,
+            set_presence: true,
+            timeout: Some(DEFAULT_SYNC_TIMEOUT),
+        }
+    }
+
+    /// Sets the `since` token to resume syncing from.
+    pub fn token(mut self, since: impl Into<String>) -> Self {
+        self.since = Some(since.into());
+        self
+    }
+
+    /// Sets the filter to apply to the sync.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Sets whether the response should include the full state for every room the user is in,
+    /// rather than only what changed since `since`.
+    pub fn full_state(mut self, full_state: bool) -> Self {
+        self.full_state = full_state;
+        self
+    }
+
+    /// Sets whether to update the user's presence status as a result of this call.
+    pub fn set_presence(mut self, set_presence: bool) -> Self {
+        self.set_presence = set_presence;
+        self
+    }
+
+    /// Sets how long the server should long-poll for new events before returning an empty
+    /// response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returned from a `Client::sync_forever` callback to decide whether the loop should keep
+/// syncing or stop.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LoopCtrl {
+    /// Keep syncing.
+    Continue,
+    /// Stop the loop.
+    Break,
+}